@@ -1,21 +1,63 @@
 use axum::body::{Body, Bytes};
 use axum::extract::rejection::JsonRejection;
-use axum::extract::{Multipart, Path, Query, Request, State};
-use axum::middleware::{from_fn, map_request, Next};
+use axum::extract::{FromRef, FromRequestParts, Multipart, Path, Query, Request, State};
+use axum::middleware::{from_fn, from_fn_with_state, map_request, Next};
 use axum::response::{IntoResponse, Response};
 use axum::routing::{get, post};
 use axum::{serve, Extension, Form, Json, Router};
-use axum_extra::extract::cookie::Cookie;
+use axum_extra::extract::cookie::{Cookie, Key, SignedCookieJar};
 use axum_extra::extract::CookieJar;
 use axum_test::multipart::{MultipartForm, Part};
 use axum_test::TestServer;
-use http::{HeaderMap, HeaderValue, Method, StatusCode, Uri};
+use axum_extra::headers::{self, Header};
+use axum_extra::TypedHeader;
+use http::request::Parts;
+use http::{HeaderMap, HeaderName, HeaderValue, Method, StatusCode, Uri};
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header as JwtHeader, Validation};
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration as StdDuration;
 use anyhow::anyhow;
 use axum::error_handling::HandleError;
+use sqlx::sqlite::SqlitePoolOptions;
+use sqlx::SqlitePool;
+use time::{Duration, OffsetDateTime};
 use tokio::net::TcpListener;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+use uuid::Uuid;
+
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("failed to install Ctrl+C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
+
+async fn run_server(listener: TcpListener, app: Router) -> std::io::Result<()> {
+    serve(listener, app)
+        .with_graceful_shutdown(shutdown_signal())
+        .await
+}
 
 #[tokio::main]
 async fn main() {
@@ -23,7 +65,7 @@ async fn main() {
 
     let listener = TcpListener::bind("0.0.0.0:3000").await.unwrap();
 
-    serve(listener, app).await.unwrap();
+    run_server(listener, app).await.unwrap();
 }
 
 #[tokio::test]
@@ -127,6 +169,58 @@ async fn test_header() {
     response.assert_text("Hello Eko");
 }
 
+struct XRequestId(String);
+
+impl Header for XRequestId {
+    fn name() -> &'static HeaderName {
+        static NAME: HeaderName = HeaderName::from_static("x-request-id");
+        &NAME
+    }
+
+    fn decode<'i, I>(values: &mut I) -> Result<Self, headers::Error>
+    where
+        I: Iterator<Item = &'i HeaderValue>,
+    {
+        let value = values.next().ok_or_else(headers::Error::invalid)?;
+        let value = value.to_str().map_err(|_| headers::Error::invalid())?;
+
+        Ok(XRequestId(value.to_string()))
+    }
+
+    fn encode<E: Extend<HeaderValue>>(&self, values: &mut E) {
+        if let Ok(value) = HeaderValue::from_str(&self.0) {
+            values.extend(std::iter::once(value));
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_typed_header() {
+    async fn hello_world(TypedHeader(request_id): TypedHeader<XRequestId>) -> String {
+        format!("Hello {}", request_id.0)
+    }
+
+    let app = Router::new().route("/get", get(hello_world));
+
+    let server = TestServer::new(app).unwrap();
+    let response = server.get("/get").add_header("x-request-id", "12345").await;
+    response.assert_status_ok();
+    response.assert_text("Hello 12345");
+}
+
+#[tokio::test]
+async fn test_typed_header_missing() {
+    async fn hello_world(TypedHeader(request_id): TypedHeader<XRequestId>) -> String {
+        format!("Hello {}", request_id.0)
+    }
+
+    let app = Router::new().route("/get", get(hello_world));
+
+    let server = TestServer::new(app).unwrap();
+    let response = server.get("/get").await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+}
+
 #[tokio::test]
 async fn test_path_parameter() {
     async fn hello_world(Path((id, id_category)): Path<(String, String)>) -> String {
@@ -391,10 +485,14 @@ async fn log_middleware(request: Request, next: Next) -> Response {
 }
 
 async fn request_id_middleware<T>(mut request: Request<T>) -> Request<T> {
-    let request_id = "12345";
-    request
-        .headers_mut()
-        .insert("X-Request-Id", request_id.parse().unwrap());
+    let request_id = XRequestId("12345".to_string());
+    let mut values: Vec<HeaderValue> = Vec::new();
+    request_id.encode(&mut values);
+
+    for value in values {
+        request.headers_mut().insert(XRequestId::name(), value);
+    }
+
     request
 }
 
@@ -420,6 +518,84 @@ async fn test_middleware() {
     response.assert_text("Hello GET 12345");
 }
 
+fn cors_layer() -> CorsLayer {
+    CorsLayer::new()
+        .allow_origin(AllowOrigin::list([HeaderValue::from_static(
+            "https://example.com",
+        )]))
+        .allow_methods([Method::GET, Method::POST])
+        .allow_headers([http::header::CONTENT_TYPE, http::header::AUTHORIZATION])
+        .expose_headers([http::header::CONTENT_TYPE])
+        .allow_credentials(true)
+        .max_age(StdDuration::from_secs(3600))
+}
+
+fn permissive_cors_layer() -> CorsLayer {
+    CorsLayer::permissive()
+}
+
+#[tokio::test]
+async fn test_cors_preflight_allowed_origin() {
+    async fn hello_world() -> String {
+        "Hello".to_string()
+    }
+
+    let app = Router::new()
+        .route("/get", get(hello_world))
+        .layer(cors_layer());
+
+    let server = TestServer::new(app).unwrap();
+    let response = server
+        .method(Method::OPTIONS, "/get")
+        .add_header("Origin", "https://example.com")
+        .add_header("Access-Control-Request-Method", "GET")
+        .await;
+
+    response.assert_status_ok();
+    response.assert_header("Access-Control-Allow-Origin", "https://example.com");
+}
+
+#[tokio::test]
+async fn test_cors_disallowed_origin_rejected() {
+    async fn hello_world() -> String {
+        "Hello".to_string()
+    }
+
+    let app = Router::new()
+        .route("/get", get(hello_world))
+        .layer(cors_layer());
+
+    let server = TestServer::new(app).unwrap();
+    let response = server
+        .method(Method::OPTIONS, "/get")
+        .add_header("Origin", "https://evil.com")
+        .add_header("Access-Control-Request-Method", "GET")
+        .await;
+
+    assert!(!response.headers().contains_key("Access-Control-Allow-Origin"));
+}
+
+#[tokio::test]
+async fn test_cors_permissive_allows_any_origin() {
+    async fn hello_world() -> String {
+        "Hello".to_string()
+    }
+
+    let app = Router::new()
+        .route("/get", get(hello_world))
+        .layer(permissive_cors_layer());
+
+    let server = TestServer::new(app).unwrap();
+    let response = server
+        .method(Method::OPTIONS, "/get")
+        .add_header("Origin", "https://evil.com")
+        .add_header("Access-Control-Request-Method", "GET")
+        .await;
+
+    response.assert_status_ok();
+    response.assert_header("Access-Control-Allow-Origin", "*");
+}
+
 struct AppError {
     code: i32,
     message: String,
@@ -593,4 +769,481 @@ async fn test_multiple_route_nest() {
     let response = server.get("/api/products/second").await;
     response.assert_status_ok();
     response.assert_text("Hello GET");
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Session {
+    id: String,
+    data: HashMap<String, String>,
+    expires_at: i64,
+}
+
+trait SessionStore {
+    async fn migrate(&self) -> anyhow::Result<()>;
+    async fn store(&self, session: &Session) -> anyhow::Result<()>;
+    async fn load(&self, id: &str) -> anyhow::Result<Option<Session>>;
+    async fn destroy(&self, id: &str) -> anyhow::Result<()>;
+}
+
+#[derive(Clone)]
+struct SqliteSessionStore {
+    pool: SqlitePool,
+}
+
+impl SqliteSessionStore {
+    async fn new(url: &str) -> anyhow::Result<Self> {
+        // A dedicated in-memory database is created per physical connection,
+        // so the pool must stay at a single connection or concurrent
+        // requests land on a database the `sessions` table was never created on.
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect(url)
+            .await?;
+        Ok(Self { pool })
+    }
+}
+
+impl SessionStore for SqliteSessionStore {
+    async fn migrate(&self) -> anyhow::Result<()> {
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS sessions (
+                id TEXT PRIMARY KEY,
+                data TEXT NOT NULL,
+                expires_at INTEGER NOT NULL
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn store(&self, session: &Session) -> anyhow::Result<()> {
+        let data = serde_json::to_string(&session.data)?;
+        sqlx::query(
+            "INSERT INTO sessions (id, data, expires_at) VALUES (?, ?, ?)
+             ON CONFLICT(id) DO UPDATE SET data = excluded.data, expires_at = excluded.expires_at",
+        )
+        .bind(&session.id)
+        .bind(data)
+        .bind(session.expires_at)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn load(&self, id: &str) -> anyhow::Result<Option<Session>> {
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        let row = sqlx::query_as::<_, (String, String, i64)>(
+            "SELECT id, data, expires_at FROM sessions WHERE id = ? AND expires_at > ?",
+        )
+        .bind(id)
+        .bind(now)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(|(id, data, expires_at)| {
+            Ok(Session {
+                id,
+                data: serde_json::from_str(&data)?,
+                expires_at,
+            })
+        })
+        .transpose()
+    }
+
+    async fn destroy(&self, id: &str) -> anyhow::Result<()> {
+        sqlx::query("DELETE FROM sessions WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+}
+
+async fn session_middleware(
+    State(state): State<AppState>,
+    jar: SignedCookieJar,
+    mut request: Request,
+    next: Next,
+) -> (SignedCookieJar, Response) {
+    let existing = match jar.get("session_id") {
+        Some(cookie) => match state.sessions.load(cookie.value()).await {
+            Ok(session) => session,
+            Err(err) => {
+                eprintln!("failed to load session: {}", err);
+                None
+            }
+        },
+        None => None,
+    };
+
+    let session = match existing {
+        Some(session) => session,
+        None => {
+            let session = Session {
+                id: Uuid::new_v4().to_string(),
+                data: HashMap::new(),
+                expires_at: (OffsetDateTime::now_utc() + Duration::hours(1)).unix_timestamp(),
+            };
+            let _ = state.sessions.store(&session).await;
+            session
+        }
+    };
+
+    let jar = jar.add(Cookie::new("session_id", session.id.clone()));
+    request.extensions_mut().insert(session);
+
+    let response = next.run(request).await;
+    (jar, response)
+}
+
+#[derive(Clone)]
+struct AppState {
+    users: Arc<HashMap<String, (Uuid, String)>>,
+    sessions: SqliteSessionStore,
+    key: Key,
+}
+
+impl FromRef<AppState> for Key {
+    fn from_ref(state: &AppState) -> Self {
+        state.key.clone()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct Claims {
+    sub: Uuid,
+    exp: i64,
+}
+
+const JWT_SECRET: &[u8] = b"super-secret-jwt-key";
+const JWT_TTL_MINUTES: i64 = 15;
+
+fn issue_token(user_id: Uuid) -> String {
+    let claims = Claims {
+        sub: user_id,
+        exp: (OffsetDateTime::now_utc() + Duration::minutes(JWT_TTL_MINUTES)).unix_timestamp(),
+    };
+
+    encode(
+        &JwtHeader::default(),
+        &claims,
+        &EncodingKey::from_secret(JWT_SECRET),
+    )
+    .unwrap()
+}
+
+fn unauthorized() -> AppError {
+    AppError {
+        code: 401,
+        message: "Unauthorized".to_string(),
+    }
+}
+
+impl<S> FromRequestParts<S> for Claims
+where
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let from_header = parts
+            .headers
+            .get(http::header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(|value| value.to_string());
+
+        let token = match from_header {
+            Some(token) => token,
+            None => CookieJar::from_headers(&parts.headers)
+                .get("token")
+                .map(|cookie| cookie.value().to_string())
+                .ok_or_else(unauthorized)?,
+        };
+
+        let mut validation = Validation::default();
+        validation.leeway = 0;
+
+        decode::<Claims>(&token, &DecodingKey::from_secret(JWT_SECRET), &validation)
+            .map(|data| data.claims)
+            .map_err(|_| unauthorized())
+    }
+}
+
+async fn login_handler(
+    State(state): State<AppState>,
+    Json(request): Json<LoginRequest>,
+) -> Result<(CookieJar, Json<LoginResponse>), AppError> {
+    let (user_id, password_hash) = state
+        .users
+        .get(&request.username)
+        .ok_or_else(unauthorized)?;
+
+    let parsed_hash = PasswordHash::new(password_hash).map_err(|_| unauthorized())?;
+
+    Argon2::default()
+        .verify_password(request.password.as_bytes(), &parsed_hash)
+        .map_err(|_| unauthorized())?;
+
+    let token = issue_token(*user_id);
+
+    Ok((
+        CookieJar::new().add(Cookie::new("token", token.clone())),
+        Json(LoginResponse { token }),
+    ))
+}
+
+async fn refresh_handler(claims: Claims) -> Json<LoginResponse> {
+    Json(LoginResponse {
+        token: issue_token(claims.sub),
+    })
+}
+
+async fn test_auth_state() -> (AppState, Uuid) {
+    let user_id = Uuid::new_v4();
+    let salt = SaltString::generate(&mut rand::thread_rng());
+    let password_hash = Argon2::default()
+        .hash_password(b"secret123", &salt)
+        .unwrap()
+        .to_string();
+
+    let mut users = HashMap::new();
+    users.insert("ekotaro".to_string(), (user_id, password_hash));
+
+    let sessions = SqliteSessionStore::new("sqlite::memory:").await.unwrap();
+    sessions.migrate().await.unwrap();
+
+    (
+        AppState {
+            users: Arc::new(users),
+            sessions,
+            key: Key::generate(),
+        },
+        user_id,
+    )
+}
+
+#[tokio::test]
+async fn test_login_success() {
+    let (state, _) = test_auth_state().await;
+
+    let app = Router::new()
+        .route("/login", post(login_handler))
+        .with_state(state);
+
+    let server = TestServer::new(app).unwrap();
+    let response = server
+        .post("/login")
+        .json(&LoginRequest {
+            username: "ekotaro".to_string(),
+            password: "secret123".to_string(),
+        })
+        .await;
+
+    response.assert_status_ok();
+    assert!(response.headers().contains_key("Set-Cookie"));
+}
+
+#[tokio::test]
+async fn test_login_wrong_password() {
+    let (state, _) = test_auth_state().await;
+
+    let app = Router::new()
+        .route("/login", post(login_handler))
+        .with_state(state);
+
+    let server = TestServer::new(app).unwrap();
+    let response = server
+        .post("/login")
+        .json(&LoginRequest {
+            username: "ekotaro".to_string(),
+            password: "wrong".to_string(),
+        })
+        .await;
+
+    response.assert_status(StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_refresh_missing_token() {
+    let (state, _) = test_auth_state().await;
+
+    let app = Router::new()
+        .route("/refresh", get(refresh_handler))
+        .with_state(state);
+
+    let server = TestServer::new(app).unwrap();
+    let response = server.get("/refresh").await;
+
+    response.assert_status(StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_refresh_expired_token() {
+    let (state, user_id) = test_auth_state().await;
+
+    let expired = Claims {
+        sub: user_id,
+        exp: (OffsetDateTime::now_utc() - Duration::minutes(1)).unix_timestamp(),
+    };
+    let token = encode(
+        &JwtHeader::default(),
+        &expired,
+        &EncodingKey::from_secret(JWT_SECRET),
+    )
+    .unwrap();
+
+    let app = Router::new()
+        .route("/refresh", get(refresh_handler))
+        .with_state(state);
+
+    let server = TestServer::new(app).unwrap();
+    let response = server
+        .get("/refresh")
+        .add_header("Authorization", format!("Bearer {}", token))
+        .await;
+
+    response.assert_status(StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_refresh_valid_token() {
+    let (state, user_id) = test_auth_state().await;
+    let token = issue_token(user_id);
+
+    let app = Router::new()
+        .route("/refresh", get(refresh_handler))
+        .with_state(state);
+
+    let server = TestServer::new(app).unwrap();
+    let response = server
+        .get("/refresh")
+        .add_header("Authorization", format!("Bearer {}", token))
+        .await;
+
+    response.assert_status_ok();
+}
+
+#[tokio::test]
+async fn test_session_store_roundtrip() {
+    let store = SqliteSessionStore::new("sqlite::memory:").await.unwrap();
+    store.migrate().await.unwrap();
+
+    let session = Session {
+        id: "session-1".to_string(),
+        data: HashMap::from([("name".to_string(), "Ekotaro".to_string())]),
+        expires_at: (OffsetDateTime::now_utc() + Duration::minutes(5)).unix_timestamp(),
+    };
+    store.store(&session).await.unwrap();
+
+    let loaded = store.load("session-1").await.unwrap().unwrap();
+    assert_eq!(loaded.data.get("name").unwrap(), "Ekotaro");
+}
+
+#[tokio::test]
+async fn test_session_store_expiry_removes_session() {
+    let store = SqliteSessionStore::new("sqlite::memory:").await.unwrap();
+    store.migrate().await.unwrap();
+
+    let session = Session {
+        id: "session-2".to_string(),
+        data: HashMap::new(),
+        expires_at: (OffsetDateTime::now_utc() - Duration::minutes(1)).unix_timestamp(),
+    };
+    store.store(&session).await.unwrap();
+
+    assert!(store.load("session-2").await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_session_store_destroy() {
+    let store = SqliteSessionStore::new("sqlite::memory:").await.unwrap();
+    store.migrate().await.unwrap();
+
+    let session = Session {
+        id: "session-3".to_string(),
+        data: HashMap::new(),
+        expires_at: (OffsetDateTime::now_utc() + Duration::minutes(5)).unix_timestamp(),
+    };
+    store.store(&session).await.unwrap();
+    store.destroy("session-3").await.unwrap();
+
+    assert!(store.load("session-3").await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_session_middleware_persists_across_requests() {
+    async fn remember_name(
+        State(state): State<AppState>,
+        Extension(mut session): Extension<Session>,
+    ) -> String {
+        match session.data.get("name") {
+            Some(name) => name.clone(),
+            None => {
+                session.data.insert("name".to_string(), "Ekotaro".to_string());
+                state.sessions.store(&session).await.unwrap();
+                "set".to_string()
+            }
+        }
+    }
+
+    let sessions = SqliteSessionStore::new("sqlite::memory:").await.unwrap();
+    sessions.migrate().await.unwrap();
+
+    let state = AppState {
+        users: Arc::new(HashMap::new()),
+        sessions,
+        key: Key::generate(),
+    };
+
+    let app = Router::new()
+        .route("/get", get(remember_name))
+        .layer(from_fn_with_state(state.clone(), session_middleware))
+        .with_state(state);
+
+    let server = TestServer::new(app).unwrap();
+    let first = server.get("/get").await;
+    first.assert_status_ok();
+    first.assert_text("set");
+
+    let cookie = first
+        .headers()
+        .get("Set-Cookie")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .split(';')
+        .next()
+        .unwrap()
+        .to_string();
+
+    // Sending back the signed `session_id` cookie makes the middleware load
+    // the stored session, so the name written on the first request is read back.
+    let second = server.get("/get").add_header("Cookie", cookie).await;
+    second.assert_status_ok();
+    second.assert_text("Ekotaro");
+}
+
+#[tokio::test]
+async fn test_run_server_graceful_shutdown() {
+    let app = Router::new().route("/", get(|| async { "Hello, world!" }));
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server = tokio::spawn(run_server(listener, app));
+
+    let response = reqwest::get(format!("http://{}", addr)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.text().await.unwrap(), "Hello, world!");
+
+    // Exercises the SIGTERM branch of `shutdown_signal`'s select directly,
+    // since `tokio::signal::unix::signal` intercepts the signal instead of
+    // letting it terminate the process once a handler is installed.
+    unsafe {
+        libc::kill(libc::getpid(), libc::SIGTERM);
+    }
+
+    server.await.unwrap().unwrap();
 }
\ No newline at end of file